@@ -36,15 +36,16 @@ use frame_support::{dispatch, ensure};
 
 use common::balance;
 use common::prelude::{Balance, FixedWrapper};
+use common::SwapRulesValidation;
 use frame_support::debug;
 
-use crate::{to_balance, to_fixed_wrapper};
+use crate::to_fixed_wrapper;
 
 use crate::bounds::*;
 
 use crate::aliases::{AccountIdOf, AssetIdOf, TechAccountIdOf};
 use crate::operations::*;
-use crate::{Config, Error, Module};
+use crate::{Config, Error, Module, PendingActions};
 
 impl<T: Config> common::SwapRulesValidation<AccountIdOf<T>, TechAccountIdOf<T>, T>
     for PairSwapAction<AssetIdOf<T>, Balance, AccountIdOf<T>, TechAccountIdOf<T>>
@@ -306,22 +307,45 @@ impl<T: Config> common::SwapRulesValidation<AccountIdOf<T>, TechAccountIdOf<T>,
         // Also this computation of only things that is for security of pool, and not for applying values, so
         // this check can be simpler than actual transfering of values.
         let pool_is_valid_after_op_test = {
-            let fxw_x =
-                to_fixed_wrapper!(balance_st) + to_fixed_wrapper!(self.source.amount.unwrap());
-            let fxw_y =
-                to_fixed_wrapper!(balance_tt) - to_fixed_wrapper!(self.destination.amount.unwrap());
-            let fxw_before = to_fixed_wrapper!(balance_st) / to_fixed_wrapper!(balance_tt);
-            let fxw_after = fxw_x / fxw_y;
-            let mut fxw_diff = fxw_after - fxw_before;
-            fxw_diff = fxw_diff.clone() * fxw_diff.clone();
-            let diff: u128 = to_balance!(fxw_diff);
-            let value = diff < balance!(100);
-            if !value {
-                debug::warn!(
-                    "Potential swap operation is blocked because pool became invalid after it"
-                );
+            // Constant-product invariant: the product of the reserves must not shrink. The fee is
+            // paid to the separate fee account, so it is left out of the deltas below. A small
+            // relative tolerance `ε` from `Config` absorbs rounding.
+            if balance_st == 0 || balance_tt == 0 {
+                // Degenerate pool, nothing sensible to compare against; earlier checks already
+                // reject empty pools, so short-circuit as valid here.
+                true
+            } else {
+                // Input actually added to the source reserve, net of the source-side fee, and the
+                // output removed from the destination reserve.
+                let delta_in_after_fee = if self.get_fee_from_destination.unwrap() {
+                    to_fixed_wrapper!(self.source.amount.unwrap())
+                } else {
+                    to_fixed_wrapper!(self.source.amount.unwrap())
+                        - to_fixed_wrapper!(self.fee.unwrap())
+                };
+                let delta_out = to_fixed_wrapper!(self.destination.amount.unwrap());
+                let fxw_k_before = to_fixed_wrapper!(balance_st) * to_fixed_wrapper!(balance_tt);
+                let fxw_k_after = (to_fixed_wrapper!(balance_st) + delta_in_after_fee)
+                    * (to_fixed_wrapper!(balance_tt) - delta_out);
+                // Allow `k_after` to fall short of `k_before` by at most the relative tolerance.
+                // `FeeInvariantTolerance` is a `Balance` fraction in the same 1e18 scale as
+                // `balance!(1)`, so `(1 - ε)` reads as a real-number fraction once both are lifted
+                // into `FixedWrapper` with the file's `to_fixed_wrapper!` idiom.
+                let fxw_one = to_fixed_wrapper!(balance!(1));
+                let fxw_tolerance = to_fixed_wrapper!(T::FeeInvariantTolerance::get());
+                let fxw_min_k_after = fxw_k_before.clone() * (fxw_one - fxw_tolerance);
+                let value = fxw_k_after.clone() >= fxw_min_k_after;
+                if !value {
+                    // Keep the product in `FixedWrapper`: `k ≈ balance_st * balance_tt` is ~1e36
+                    // scale and would overflow `u128` if round-tripped through `to_balance!`.
+                    debug::warn!(
+                        "Potential swap operation is blocked because pool became invalid after it: k_before={:?}, k_after={:?}",
+                        fxw_k_before.get(),
+                        fxw_k_after.get()
+                    );
+                }
+                value
             }
-            value
         };
         ensure!(
             pool_is_valid_after_op_test,
@@ -330,10 +354,11 @@ impl<T: Config> common::SwapRulesValidation<AccountIdOf<T>, TechAccountIdOf<T>,
         Ok(())
     }
     fn instant_auto_claim_used(&self) -> bool {
-        true
+        // Instant settlement is the default; deferred swaps are claimed on trigger.
+        self.mode == SwapMode::Instant
     }
     fn triggered_auto_claim_used(&self) -> bool {
-        false
+        self.mode == SwapMode::Deferred
     }
     fn is_able_to_claim(&self) -> bool {
         true
@@ -345,12 +370,94 @@ impl<T: Config> common::SwapAction<AccountIdOf<T>, TechAccountIdOf<T>, T>
 {
     /// This function is called after validation, and every `Option` is `Some`, and it is safe to do
     /// unwrap. `Bounds` is also safe to unwrap.
+    ///
+    /// In `SwapMode::Instant` the swap is settled immediately. In `SwapMode::Deferred` only a named
+    /// hold is placed on the source account's input asset, and the actual pool transfers are
+    /// deferred to [`claim`](Self::claim) (or released by [`cancel`](Self::cancel)).
     fn reserve(&self, source: &AccountIdOf<T>) -> dispatch::DispatchResult {
+        if Some(source) != self.client_account.as_ref() {
+            let e = Error::<T>::SourceAndClientAccountDoNotMatchAsEqual.into();
+            return Err(e);
+        }
+        match self.mode {
+            SwapMode::Instant => self.settle(source),
+            SwapMode::Deferred => common::with_transaction(|| {
+                // Tag the hold with this action's reason so multiple pending swaps on the same
+                // account are individually releasable and cannot collide. The reason is derived
+                // from the action itself, so two identical pending swaps would share it; the second
+                // one is rejected here with `PendingSwapAlreadyExists` before any hold is placed.
+                let reason = Module::<T>::hold_reason_for(self);
+                ensure!(
+                    !PendingActions::<T>::contains_key(&reason),
+                    Error::<T>::PendingSwapAlreadyExists
+                );
+                <assets::Module<T>>::hold(
+                    &self.source.asset,
+                    &reason,
+                    source,
+                    self.source.amount.unwrap(),
+                )?;
+                PendingActions::<T>::insert(&reason, self.clone());
+                Ok(())
+            }),
+        }
+    }
+    fn claim(&self, source: &AccountIdOf<T>) -> bool {
+        match self.mode {
+            // Instant swaps are already settled in `reserve`.
+            SwapMode::Instant => true,
+            SwapMode::Deferred => common::with_transaction(|| {
+                let reason = Module::<T>::hold_reason_for(self);
+                // Re-validate against the current reserves before settling: the quote captured at
+                // `reserve` time is stale if the pool moved in between, and settling it blindly
+                // could violate the constant-product invariant. `prepare_and_validate` re-runs that
+                // check (and checks the stored fee against the recommended one) on a clone, leaving
+                // the stored action untouched.
+                let mut revalidated = self.clone();
+                revalidated.prepare_and_validate(Some(source))?;
+                // Release the held input back to the source, then settle the swap as usual.
+                <assets::Module<T>>::release(
+                    &self.source.asset,
+                    &reason,
+                    source,
+                    self.source.amount.unwrap(),
+                    false,
+                )?;
+                revalidated.settle(source)?;
+                PendingActions::<T>::remove(&reason);
+                Ok(())
+            })
+            .is_ok(),
+        }
+    }
+    fn weight(&self) -> Weight {
+        // Settlement is a bounded, fixed set of asset transfers and one reserve update; the
+        // deferred path adds a single hold/release. Charge the pallet's configured swap weight.
+        T::GetSwapActionWeight::get()
+    }
+    fn cancel(&self, source: &AccountIdOf<T>) {
+        if self.mode == SwapMode::Deferred {
+            let reason = Module::<T>::hold_reason_for(self);
+            // Releasing the hold restores the user's balance; the swap never touched the pool.
+            let _ = <assets::Module<T>>::release(
+                &self.source.asset,
+                &reason,
+                source,
+                self.source.amount.unwrap(),
+                false,
+            );
+            PendingActions::<T>::remove(&reason);
+        }
+    }
+}
+
+impl<T: Config> PairSwapAction<AssetIdOf<T>, Balance, AccountIdOf<T>, TechAccountIdOf<T>> {
+    /// Execute the pool transfers and reserve update for an already-validated action.
+    ///
+    /// Shared between instant `reserve` and triggered `claim` so both settlement paths
+    /// stay byte-for-byte identical.
+    fn settle(&self, source: &AccountIdOf<T>) -> dispatch::DispatchResult {
         common::with_transaction(|| {
-            if Some(source) != self.client_account.as_ref() {
-                let e = Error::<T>::SourceAndClientAccountDoNotMatchAsEqual.into();
-                return Err(e);
-            }
             ensure!(
                 Some(source) == self.client_account.as_ref(),
                 Error::<T>::SourceAndClientAccountDoNotMatchAsEqual
@@ -358,6 +465,48 @@ impl<T: Config> common::SwapAction<AccountIdOf<T>, TechAccountIdOf<T>, T>
             let fee_account_repr_sys = technical::Module::<T>::tech_account_id_to_account_id(
                 self.fee_account.as_ref().unwrap(),
             )?;
+            let pool_account_repr_sys =
+                technical::Module::<T>::tech_account_id_to_account_id(&self.pool_account)?;
+
+            // Existential-deposit guards, enforced before any value moves so a rejected swap
+            // leaves no partial state (the whole body runs inside `with_transaction`).
+            let dest_min = <assets::Module<T>>::minimum_balance(&self.destination.asset)?;
+            // The net amount the receiver is credited differs between the fee branches.
+            let receiver_net = if self.get_fee_from_destination.unwrap() {
+                self.destination.amount.unwrap() - self.fee.unwrap()
+            } else {
+                self.destination.amount.unwrap()
+            };
+            let receiver_exists = <assets::Module<T>>::free_balance(
+                &self.destination.asset,
+                self.receiver_account.as_ref().unwrap(),
+            )? > 0;
+            ensure!(
+                receiver_exists || receiver_net >= dest_min,
+                Error::<T>::DestinationBalanceBelowExistentialDeposit
+            );
+            // In the fee-from-destination branch the pool also pays the destination-side fee out
+            // to the fee account; hold it to the same exists-or-`>= dest_min` rule as the receiver.
+            if self.get_fee_from_destination.unwrap() {
+                let fee_account_exists = <assets::Module<T>>::free_balance(
+                    &self.destination.asset,
+                    &fee_account_repr_sys,
+                )? > 0;
+                ensure!(
+                    fee_account_exists || self.fee.unwrap() >= dest_min,
+                    Error::<T>::DestinationBalanceBelowExistentialDeposit
+                );
+            }
+            // Guard the pool account against being reaped: it must keep at least its own
+            // existential deposit in the destination asset after paying out.
+            let pool_dest_balance =
+                <assets::Module<T>>::free_balance(&self.destination.asset, &pool_account_repr_sys)?;
+            let pool_dest_out = self.destination.amount.unwrap();
+            ensure!(
+                pool_dest_balance >= pool_dest_out
+                    && pool_dest_balance - pool_dest_out >= dest_min,
+                Error::<T>::PoolBalanceBelowExistentialDeposit
+            );
 
             if self.get_fee_from_destination.unwrap() {
                 technical::Module::<T>::transfer_in(
@@ -399,8 +548,6 @@ impl<T: Config> common::SwapAction<AccountIdOf<T>, TechAccountIdOf<T>, T>
                 )?;
             }
 
-            let pool_account_repr_sys =
-                technical::Module::<T>::tech_account_id_to_account_id(&self.pool_account)?;
             let balance_a =
                 <assets::Module<T>>::free_balance(&self.source.asset, &pool_account_repr_sys)?;
             let balance_b =
@@ -413,13 +560,4 @@ impl<T: Config> common::SwapAction<AccountIdOf<T>, TechAccountIdOf<T>, T>
             Ok(())
         })
     }
-    fn claim(&self, _source: &AccountIdOf<T>) -> bool {
-        true
-    }
-    fn weight(&self) -> Weight {
-        unimplemented!()
-    }
-    fn cancel(&self, _source: &AccountIdOf<T>) {
-        unimplemented!()
-    }
 }
\ No newline at end of file
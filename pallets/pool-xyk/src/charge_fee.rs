@@ -0,0 +1,209 @@
+// This file is part of the SORA network and Polkaswap app.
+
+// Copyright (c) 2020, 2021, Polka Biome Ltd. All rights reserved.
+// SPDX-License-Identifier: BSD-4-Clause
+
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+
+// Redistributions of source code must retain the above copyright notice, this list
+// of conditions and the following disclaimer.
+// Redistributions in binary form must reproduce the above copyright notice, this
+// list of conditions and the following disclaimer in the documentation and/or other
+// materials provided with the distribution.
+//
+// All advertising materials mentioning features or use of this software must display
+// the following acknowledgement: This product includes software developed by Polka Biome
+// Ltd., SORA, and Polkaswap.
+//
+// Neither the name of the Polka Biome Ltd. nor the names of its contributors may be used
+// to endorse or promote products derived from this software without specific prior written permission.
+
+// THIS SOFTWARE IS PROVIDED BY Polka Biome Ltd. AS IS AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL Polka Biome Ltd. BE LIABLE FOR ANY
+// DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING,
+// BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS;
+// OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT,
+// STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Transaction payment adapter that lets users pay fees in any asset that has a
+//! liquidity pool against the native fee token.
+//!
+//! At `withdraw_fee` time the adapter swaps just enough of the user's asset into
+//! the pool to obtain the exact native fee amount, mirroring the asset-conversion
+//! fee mechanism: `calc_input_for_exact_output` decides how much input is required,
+//! a `PairSwapAction` with `destination.amount = Bounds::Desired(native_fee)` and
+//! `source.amount = Bounds::Max(user_max)` performs the swap, and the resulting
+//! native tokens are sent to the fee collector account. At `correct_and_deposit_fee`
+//! time the finalized fee is deposited to the block author and any overpayment is
+//! refunded through a reverse quote.
+
+use frame_support::unsigned::TransactionValidityError;
+use sp_runtime::traits::{Saturating, Zero};
+use sp_runtime::transaction_validity::InvalidTransaction;
+
+use common::prelude::Balance;
+use common::SwapAction;
+use common::SwapRulesValidation;
+
+use crate::bounds::*;
+use crate::aliases::{AccountIdOf, AssetIdOf, TechAccountIdOf};
+use crate::operations::*;
+use crate::{Config, Error, Module};
+
+/// Liability accrued during `withdraw_fee`, settled in `correct_and_deposit_fee`.
+///
+/// It carries the asset the user actually paid in and the amount of that asset
+/// that was pulled into the pool, so the post-dispatch step can refund any
+/// overpayment via a reverse quote.
+pub struct LiquidityInfo<T: Config> {
+    /// Asset the fee was charged in.
+    paid_asset: AssetIdOf<T>,
+    /// Amount of `paid_asset` the user spent to obtain `withdrawn`.
+    spent: Balance,
+    /// Native tokens obtained from the swap; the finalized fee out of it goes to the
+    /// block author and the remainder is refunded.
+    withdrawn: Balance,
+}
+
+/// Charge transaction fees in an arbitrary pool asset, chosen per transaction.
+///
+/// This mirrors `pallet_asset_tx_payment`'s `OnChargeAssetTransaction`: the asset the
+/// signer elected to pay with is threaded in as `asset_id` by the accompanying signed
+/// extension, so a single runtime configuration supports every listed asset for gas
+/// rather than one hardcoded `Get`.
+pub trait OnChargeAssetTransaction<T: Config> {
+    /// Asset the fee is charged in, selected per transaction.
+    type AssetId;
+    /// Opaque liability threaded from `withdraw_fee` to `correct_and_deposit_fee`.
+    type LiquidityInfo;
+
+    fn withdraw_fee(
+        who: &AccountIdOf<T>,
+        asset_id: Self::AssetId,
+        fee: Balance,
+    ) -> Result<Self::LiquidityInfo, TransactionValidityError>;
+
+    fn correct_and_deposit_fee(
+        who: &AccountIdOf<T>,
+        corrected_fee: Balance,
+        already_withdrawn: Self::LiquidityInfo,
+    ) -> Result<(), TransactionValidityError>;
+}
+
+/// Routes transaction fees through a `PairSwapAction` against the native fee token.
+///
+/// The pool to swap against is chosen from the per-transaction `asset_id`, so any asset
+/// with a pool against the native token is usable for gas.
+pub struct SwapFeeAdapter<T>(sp_std::marker::PhantomData<T>);
+
+impl<T: Config> SwapFeeAdapter<T> {
+    /// Build the action that swaps `who`'s `fee_asset` into the exact `native_fee`.
+    ///
+    /// The input bound is `Bounds::Max(user_max)`, so the swap fails cleanly
+    /// rather than overspending when the required input exceeds what the user
+    /// is willing to part with.
+    fn build_action(
+        who: &AccountIdOf<T>,
+        fee_asset: AssetIdOf<T>,
+        native_fee: Balance,
+        user_max: Balance,
+    ) -> Result<
+        PairSwapAction<AssetIdOf<T>, Balance, AccountIdOf<T>, TechAccountIdOf<T>>,
+        Error<T>,
+    > {
+        let pool_account = Module::<T>::pool_account_for(fee_asset, T::GetNativeAssetId::get())?;
+        Ok(PairSwapAction {
+            client_account: Some(who.clone()),
+            receiver_account: Some(Module::<T>::fee_collector_account()?),
+            pool_account,
+            source: Resource {
+                asset: fee_asset,
+                amount: Bounds::Max(user_max),
+            },
+            destination: Resource {
+                asset: T::GetNativeAssetId::get(),
+                amount: Bounds::Desired(native_fee),
+            },
+            fee: None,
+            fee_account: None,
+            get_fee_from_destination: None,
+            // Fees are settled synchronously; no deferred hold is wanted here.
+            mode: SwapMode::Instant,
+        })
+    }
+}
+
+impl<T: Config> OnChargeAssetTransaction<T> for SwapFeeAdapter<T> {
+    type AssetId = AssetIdOf<T>;
+    type LiquidityInfo = Option<LiquidityInfo<T>>;
+
+    fn withdraw_fee(
+        who: &AccountIdOf<T>,
+        asset_id: Self::AssetId,
+        fee: Balance,
+    ) -> Result<Self::LiquidityInfo, TransactionValidityError> {
+        if fee.is_zero() {
+            return Ok(None);
+        }
+
+        // Bound the input by the user's whole free balance of the fee asset; building
+        // the action fails cleanly when there is no pool for the pair.
+        let user_max = <assets::Module<T>>::free_balance(&asset_id, who)
+            .map_err(|_| InvalidTransaction::Payment)?;
+        let mut action = Self::build_action(who, asset_id, fee, user_max)
+            .map_err(|_| InvalidTransaction::Payment)?;
+
+        // `prepare_and_validate` fills in the calculated input and rejects the swap
+        // if it would exceed `Bounds::Max` or drain an empty pool; `reserve` then
+        // performs the transfers atomically (`with_transaction`), so a failure here
+        // leaves no partial state behind.
+        action
+            .prepare_and_validate(Some(who))
+            .map_err(|_| InvalidTransaction::Payment)?;
+        let spent = action.source.amount.unwrap();
+        action
+            .reserve(who)
+            .map_err(|_| InvalidTransaction::Payment)?;
+
+        // The native tokens now sit on the fee collector. The author is paid in
+        // `correct_and_deposit_fee` once the final fee is known, so value stays conserved
+        // even when the actual fee is lower than this pre-dispatch estimate.
+        Ok(Some(LiquidityInfo {
+            paid_asset: asset_id,
+            spent,
+            withdrawn: fee,
+        }))
+    }
+
+    fn correct_and_deposit_fee(
+        who: &AccountIdOf<T>,
+        corrected_fee: Balance,
+        already_withdrawn: Self::LiquidityInfo,
+    ) -> Result<(), TransactionValidityError> {
+        if let Some(paid) = already_withdrawn {
+            // Pay the author only the finalized fee out of the native acquired up front.
+            let corrected = corrected_fee.min(paid.withdrawn);
+            Module::<T>::deposit_fee_to_author(corrected)
+                .map_err(|_| InvalidTransaction::Payment)?;
+
+            // Refund the native remainder. Re-quoting it back through the pool returns the
+            // user's own asset, so the author, the user and the pool all net out to zero.
+            let refund_native = paid.withdrawn.saturating_sub(corrected);
+            if !refund_native.is_zero() {
+                let refund_asset = Module::<T>::quote_reverse(
+                    paid.paid_asset,
+                    T::GetNativeAssetId::get(),
+                    refund_native,
+                )
+                .map_err(|_| InvalidTransaction::Payment)?
+                .min(paid.spent);
+                Module::<T>::refund_fee(who, &paid.paid_asset, refund_native, refund_asset)
+                    .map_err(|_| InvalidTransaction::Payment)?;
+            }
+        }
+        Ok(())
+    }
+}